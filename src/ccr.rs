@@ -0,0 +1,147 @@
+//! Closed-circuit rebreather (CCR) support, planned against a fixed PPO2
+//! setpoint rather than a fixed bottom gas mix.
+//!
+//! The ZHL16 loading itself is still computed by [`OpenCircuit`](capra::plan::open_circuit::OpenCircuit):
+//! at each depth we substitute the bottom gas with the gas an open-circuit
+//! diver would need to breathe to reproduce the same inspired partial
+//! pressures a CCR diver gets from `setpoint` ata of O2 plus diluent. ZHL16
+//! loading only depends on those partial pressures, so this reproduces the
+//! correct tissue loading without needing to touch the decompression engine
+//! itself. Gas consumption, however, is modelled separately: a rebreather
+//! burns O2 metabolically (not by ambient-pressure-scaled SAC) and tops up
+//! diluent only to keep the counterlung volume constant as ambient pressure
+//! changes.
+//!
+//! **Known limitation**: the setpoint substitution above is only applied to
+//! the *bottom* segments built from user input (see `equivalent_gas`'s call
+//! site in `main.rs`). The ascent and deco-stop segments the decompression
+//! engine generates afterwards are still planned against the real
+//! open-circuit `deco_gases` the dive supplied, using ordinary open-circuit
+//! PPO2, not a setpoint-driven model. A CCR diver would actually stay on
+//! setpoint through ascent and deco too (injecting O2 to hold it, or
+//! breathing off a richer deco gas at a lower setpoint); until that's
+//! modelled, ZHL16 loading during ascent/deco is plain open-circuit rather
+//! than fixed-setpoint, so treat deco timing from a CCR plan as an
+//! approximation, not a faithful CCR deco schedule.
+use capra::environment::Environment;
+use capra::gas::Gas;
+use capra::segment::{Segment, SegmentType};
+use capra::units::depth::Depth;
+
+use crate::oxygen_toxicity::ata_at_depth;
+
+/// Rough resting-to-light-exertion metabolic O2 consumption, in litres per
+/// minute at the diver's body, independent of ambient pressure (unlike
+/// open-circuit SAC, which is quoted in surface-equivalent litres).
+const METABOLIC_O2_RATE_LPM: f64 = 1.0;
+
+/// Approximate counterlung + loop volume in litres, used to estimate the
+/// diluent needed to keep loop volume constant as ambient pressure changes.
+const LOOP_VOLUME_LITRES: f64 = 6.0;
+
+/// Builds the gas an open-circuit diver would need to breathe at `depth`
+/// to match the inspired partial pressures of a CCR diver on `diluent` at
+/// the given `setpoint`: an O2 fraction of `setpoint / ata`, clamped to no
+/// less than the diluent's own O2 fraction and no more than 100%, with the
+/// remaining fraction split between He and N2 in the same ratio as the
+/// diluent.
+pub fn equivalent_gas(diluent: Gas, depth: Depth, environment: &Environment, setpoint: f64) -> Gas {
+    let ata = ata_at_depth(depth, environment);
+    let diluent_inert = 100 - diluent.o2();
+    let o2_fraction = (setpoint / ata * 100.0).min(100.0).max(diluent.o2() as f64);
+
+    // Round o2 first, then derive he as an integer remainder of the
+    // *already-rounded* o2's complement, so the three fractions are
+    // guaranteed to sum to 100 instead of o2 and he each rounding
+    // independently and overshooting it.
+    let o2 = o2_fraction.round() as u8;
+    let remaining = 100 - o2;
+    let he = if diluent_inert == 0 {
+        0
+    } else {
+        (remaining as f64 * diluent.he() as f64 / diluent_inert as f64).round() as u8
+    }
+    .min(remaining);
+    Gas::new(o2, he, remaining - he).expect("equivalent CCR gas fractions out of range")
+}
+
+#[derive(Debug, Default)]
+pub struct CcrGasUsage {
+    pub o2_litres: i64,
+    pub diluent_litres: i64,
+}
+
+/// Estimates O2 and diluent consumption across every segment of a planned
+/// CCR dive: O2 at a constant metabolic rate, diluent only to top up the
+/// loop volume lost to compression on `AscDesc` descents. The segments'
+/// gases are not used.
+pub fn gas_used(segments: &[(Segment, Gas)], environment: &Environment) -> CcrGasUsage {
+    let mut usage = CcrGasUsage::default();
+    // Starts at the surface, not `None`, so the initial descent segment
+    // (surface to first bottom depth) is charged for its compression the
+    // same as every later descent.
+    let mut last_ata = ata_at_depth(Depth(0), environment);
+
+    for (segment, _) in segments {
+        let minutes = segment.time().whole_seconds() as f64 / 60.0;
+        usage.o2_litres += (minutes * METABOLIC_O2_RATE_LPM).round() as i64;
+
+        let ata = ata_at_depth(*segment.end_depth(), environment);
+        if let SegmentType::AscDesc = segment.segment_type() {
+            if ata > last_ata {
+                usage.diluent_litres += (LOOP_VOLUME_LITRES * (ata - last_ata)).round() as i64;
+            }
+        }
+        last_ata = ata;
+    }
+
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capra::units::altitude::Altitude;
+    use capra::units::rate::Rate;
+    use capra::units::water_density::SALTWATER;
+
+    fn sea_level() -> Environment {
+        Environment::new(SALTWATER, Altitude(0))
+    }
+
+    /// Regression test for the case from review: a 0% N2 diluent whose o2
+    /// and inert fractions both land on a `.5` boundary, so rounding each
+    /// independently would overshoot 100 and underflow the gas's n2 field.
+    #[test]
+    fn equivalent_gas_fractions_sum_to_100() {
+        let diluent = Gas::new(10, 90, 0).unwrap();
+        let gas = equivalent_gas(diluent, Depth(10), &sea_level(), 1.41);
+        assert!(gas.o2() as u16 + gas.he() as u16 <= 100);
+    }
+
+    #[test]
+    fn equivalent_gas_matches_setpoint_at_depth() {
+        let diluent = Gas::new(21, 0, 79).unwrap();
+        let environment = sea_level();
+        let gas = equivalent_gas(diluent, Depth(20), &environment, 1.2);
+        let ata = ata_at_depth(Depth(20), &environment);
+        assert_eq!(gas.o2(), (1.2 / ata * 100.0).round() as u8);
+    }
+
+    #[test]
+    fn gas_used_charges_for_surface_to_depth_compression() {
+        let environment = sea_level();
+        let descent = Segment::new(
+            SegmentType::AscDesc,
+            Depth(0),
+            Depth(20),
+            time::Duration::minutes(2),
+            Rate(-18),
+            Rate(30),
+        )
+        .unwrap();
+        let diluent = Gas::new(21, 0, 79).unwrap();
+        let usage = gas_used(&[(descent, diluent)], &environment);
+        assert!(usage.diluent_litres > 0);
+    }
+}
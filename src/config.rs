@@ -0,0 +1,128 @@
+//! `config.toml` loading for batch planning.
+//!
+//! A config lists one or more dive input files to plan in a single run,
+//! along with global defaults that each dive may individually override.
+
+use capra::units::air_consumption::AirConsumption;
+use capra::units::altitude::Altitude;
+use capra::units::rate::Rate;
+use capra::units::water_density::{WaterDensity, FRESHWATER, SALTWATER};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Defaults {
+    pub gfl: Option<u8>,
+    pub gfh: Option<u8>,
+    pub ascent_rate: Option<Rate>,
+    pub descent_rate: Option<Rate>,
+    pub bottom_sac: Option<AirConsumption>,
+    pub deco_sac: Option<AirConsumption>,
+    pub water_density: Option<String>,
+    pub altitude: Option<Altitude>,
+}
+
+impl Defaults {
+    /// Resolves the configured water density name ("saltwater"/"freshwater")
+    /// to the matching constant, defaulting to saltwater.
+    pub fn water_density(&self) -> WaterDensity {
+        match self.water_density.as_deref() {
+            Some("freshwater") => FRESHWATER,
+            _ => SALTWATER,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DiveEntry {
+    pub path: String,
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Defaults,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    #[serde(default)]
+    pub defaults: Defaults,
+    pub dives: Vec<DiveEntry>,
+}
+
+/// Merges a dive entry's per-dive overrides on top of the config's global
+/// defaults; a `None` override falls through to the global default.
+pub fn merged_defaults(config: &Config, entry: &DiveEntry) -> Defaults {
+    Defaults {
+        gfl: entry.overrides.gfl.or(config.defaults.gfl),
+        gfh: entry.overrides.gfh.or(config.defaults.gfh),
+        ascent_rate: entry.overrides.ascent_rate.or(config.defaults.ascent_rate),
+        descent_rate: entry
+            .overrides
+            .descent_rate
+            .or(config.defaults.descent_rate),
+        bottom_sac: entry.overrides.bottom_sac.or(config.defaults.bottom_sac),
+        deco_sac: entry.overrides.deco_sac.or(config.defaults.deco_sac),
+        water_density: entry
+            .overrides
+            .water_density
+            .clone()
+            .or_else(|| config.defaults.water_density.clone()),
+        altitude: entry.overrides.altitude.or(config.defaults.altitude),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_defaults_prefers_entry_override() {
+        let config = Config {
+            defaults: Defaults {
+                gfl: Some(30),
+                ..Defaults::default()
+            },
+            dives: Vec::new(),
+        };
+        let entry = DiveEntry {
+            path: "dive.json".to_string(),
+            title: None,
+            overrides: Defaults {
+                gfl: Some(45),
+                ..Defaults::default()
+            },
+        };
+
+        assert_eq!(merged_defaults(&config, &entry).gfl, Some(45));
+    }
+
+    #[test]
+    fn merged_defaults_falls_through_to_global_default() {
+        let config = Config {
+            defaults: Defaults {
+                ascent_rate: Some(Rate(-9)),
+                ..Defaults::default()
+            },
+            dives: Vec::new(),
+        };
+        let entry = DiveEntry {
+            path: "dive.json".to_string(),
+            title: None,
+            overrides: Defaults::default(),
+        };
+
+        assert_eq!(merged_defaults(&config, &entry).ascent_rate, Some(Rate(-9)));
+    }
+
+    #[test]
+    fn water_density_defaults_to_saltwater() {
+        assert_eq!(Defaults::default().water_density(), SALTWATER);
+    }
+
+    #[test]
+    fn water_density_recognizes_freshwater() {
+        let defaults = Defaults {
+            water_density: Some("freshwater".to_string()),
+            ..Defaults::default()
+        };
+        assert_eq!(defaults.water_density(), FRESHWATER);
+    }
+}
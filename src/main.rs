@@ -8,14 +8,21 @@ use capra::plan::open_circuit::OpenCircuit;
 use capra::plan::DivePlan;
 use capra::segment::{Segment, SegmentType};
 use capra::units::air_consumption::AirConsumption;
-use capra::units::altitude::Altitude;
 use capra::units::depth::Depth;
 use capra::units::rate::Rate;
-use capra::units::water_density::SALTWATER;
 use serde::{Deserialize, Serialize};
 
+mod ccr;
+mod config;
+mod output;
+mod oxygen_toxicity;
+mod plot;
+
 use std::fs;
+use std::io::{self, BufRead, BufReader};
 use std::iter::FromIterator;
+use std::path::Path;
+use std::process;
 use tabular::row;
 use tabular::Table;
 use time::Duration;
@@ -41,6 +48,19 @@ struct JSONDiveSegment {
     he: u8,
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum JSONDiveMode {
+    OpenCircuit,
+    Ccr,
+}
+
+impl Default for JSONDiveMode {
+    fn default() -> Self {
+        JSONDiveMode::OpenCircuit
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct JSONDive {
     gfl: Option<u8>,
@@ -49,6 +69,11 @@ struct JSONDive {
     desc: Option<Rate>,
     bottom_sac: Option<AirConsumption>,
     deco_sac: Option<AirConsumption>,
+    #[serde(default)]
+    mode: JSONDiveMode,
+    // PPO2 setpoint in ata; required when `mode` is `ccr`. Segment o2/he
+    // fields are then read as the CCR diluent rather than a bottom gas.
+    setpoint: Option<f64>,
     segments: Vec<JSONDiveSegment>,
     deco_gases: Vec<JSONDecoGas>,
 }
@@ -59,38 +84,221 @@ fn pretty_time(duration: &Duration) -> String {
     format!("{}:{:0>2}", m, s)
 }
 
-fn main() {
-    // let mut line: String = "".parse().unwrap();
-    // let stdin = io::stdin();
-    // for x in BufReader::new(stdin).lines() {
-    //     line = line.to_owned() + &x.expect("unable to read input") + "\n"
-    // }
+/// Returns the first non-flag argument, i.e. the positional dive path,
+/// skipping `--plot`'s and `--format`'s own values.
+fn positional_arg(args: &[String]) -> Option<&str> {
+    let mut iter = args.iter().skip(1).peekable();
+    while let Some(arg) = iter.next() {
+        if arg == "--plot" || arg == "--format" {
+            iter.next();
+            continue;
+        }
+        if arg.starts_with("--") {
+            continue;
+        }
+        return Some(arg);
+    }
+    None
+}
+
+/// True if `--json`, `--format=json`, or the two-token `--format json` is
+/// present.
+fn json_output_requested(args: &[String]) -> bool {
+    args.iter().any(|arg| arg == "--json" || arg == "--format=json")
+        || args
+            .windows(2)
+            .any(|w| w[0] == "--format" && w[1] == "json")
+}
+
+/// Derives a per-dive plot path for a batch run by inserting a slug of
+/// `title` before the file extension, so multiple dives in one `config.toml`
+/// run don't silently overwrite each other's profile.
+fn per_dive_plot_path(plot_path: Option<&Path>, title: &str) -> Option<std::path::PathBuf> {
+    let path = plot_path?;
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("profile");
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("html");
+    let slug: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    Some(path.with_file_name(format!("{}-{}.{}", stem, slug, ext)))
+}
 
-    let line = fs::read_to_string("samples/sample_sammy.json") // Use this for profiling!
-        .expect("Something went wrong reading the file");
+/// Builds a `Gas` from `o2`/`he` fractions read from untrusted input,
+/// exiting cleanly (rather than underflowing `100 - he - o2`) if they
+/// don't leave a valid N2 remainder.
+fn decode_gas(o2: u8, he: u8, context: &str) -> Gas {
+    let n2 = 100u8.checked_sub(o2).and_then(|n2| n2.checked_sub(he));
+    let n2 = n2.unwrap_or_else(|| {
+        eprintln!(
+            "error: unable to decode {}: o2 ({}) + he ({}) exceeds 100",
+            context, o2, he
+        );
+        process::exit(1);
+    });
+    Gas::new(o2, he, n2).unwrap_or_else(|e| {
+        eprintln!("error: unable to decode {}: {:?}", context, e);
+        process::exit(1);
+    })
+}
+
+/// Reads dive input JSON from `path`, or line by line from stdin when
+/// `path` is `-` or absent.
+fn read_dive_input(path: Option<&str>) -> io::Result<String> {
+    match path {
+        None | Some("-") => {
+            let stdin = io::stdin();
+            let mut contents = String::new();
+            for line in BufReader::new(stdin.lock()).lines() {
+                contents.push_str(&line?);
+                contents.push('\n');
+            }
+            Ok(contents)
+        }
+        Some(path) => fs::read_to_string(path),
+    }
+}
 
-    let js: JSONDive = serde_json::from_str(&line).expect("unable to decode user input");
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let json_output = json_output_requested(&args);
+    let plot_path = args
+        .iter()
+        .position(|arg| arg == "--plot")
+        .and_then(|i| args.get(i + 1))
+        .map(std::path::PathBuf::from);
+    let dive_path = positional_arg(&args);
 
-    let ascent_rate = js.asc.unwrap_or(DEFAULT_ASCENT_RATE);
-    let descent_rate = js.desc.unwrap_or(DEFAULT_DESCENT_RATE);
-    let sac_bottom = js.bottom_sac.unwrap_or(DEFAULT_BOTTOM_SAC);
-    let sac_deco = js.deco_sac.unwrap_or(DEFAULT_DECO_SAC);
+    let mut warned = false;
 
-    let gf = if let (Some(gfl), Some(gfh)) = (js.gfl, js.gfh) {
-        GradientFactor::new(gfl, gfh)
+    if let Some(path) = dive_path {
+        let input = read_dive_input(Some(path)).unwrap_or_else(|e| {
+            eprintln!("error: unable to read '{}': {}", path, e);
+            process::exit(1);
+        });
+        let outcome = run_dive(&input, &config::Defaults::default(), json_output, plot_path.as_deref());
+        warned = outcome.warned;
+        if let Some(json_plan) = outcome.json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json_plan).expect("unable to serialize plan")
+            );
+        }
     } else {
-        GradientFactor::default()
+        match fs::read_to_string("config.toml") {
+            Ok(config_str) => {
+                let config: config::Config =
+                    toml::from_str(&config_str).unwrap_or_else(|e| {
+                        eprintln!("error: unable to decode config.toml: {}", e);
+                        process::exit(1);
+                    });
+                let mut json_plans = Vec::new();
+                for entry in &config.dives {
+                    let title = entry.title.clone().unwrap_or_else(|| entry.path.clone());
+                    if !json_output {
+                        println!("=== {} ===\n", title);
+                    }
+                    let defaults = config::merged_defaults(&config, entry);
+                    let input = read_dive_input(Some(&entry.path)).unwrap_or_else(|e| {
+                        eprintln!("error: unable to read '{}': {}", entry.path, e);
+                        process::exit(1);
+                    });
+                    let dive_plot_path = per_dive_plot_path(plot_path.as_deref(), &title);
+                    let outcome = run_dive(&input, &defaults, json_output, dive_plot_path.as_deref());
+                    warned |= outcome.warned;
+                    json_plans.extend(outcome.json);
+                }
+                // A batch may plan several dives, so JSON output is a single
+                // array of plan documents rather than concatenated objects,
+                // keeping stdout valid JSON.
+                if json_output {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&json_plans).expect("unable to serialize plans")
+                    );
+                }
+            }
+            Err(_) => {
+                let input = read_dive_input(None).unwrap_or_else(|e| {
+                    eprintln!("error: unable to read dive input from stdin: {}", e);
+                    process::exit(1);
+                });
+                let outcome = run_dive(
+                    &input,
+                    &config::Defaults::default(),
+                    json_output,
+                    plot_path.as_deref(),
+                );
+                warned = outcome.warned;
+                if let Some(json_plan) = outcome.json {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&json_plan).expect("unable to serialize plan")
+                    );
+                }
+            }
+        }
+    }
+
+    if warned {
+        process::exit(1);
+    }
+}
+
+/// Outcome of planning a single dive: whether it warned (CNS exposure over
+/// 100%, a PPO2 over 1.6 ata, or a MOD violation; OTU never triggers a
+/// warning), and, in `--json` mode, the plan document itself so
+/// the caller can print it standalone or collect it into a batch array.
+struct RunOutcome {
+    warned: bool,
+    json: Option<output::JSONOutputPlan>,
+}
+
+/// Plans a single dive, printing table output directly unless `json_output`
+/// is set, in which case the plan document is returned instead of printed
+/// so callers can wrap multiple dives in a single JSON array.
+fn run_dive(
+    input: &str,
+    defaults: &config::Defaults,
+    json_output: bool,
+    plot_path: Option<&Path>,
+) -> RunOutcome {
+    let js: JSONDive = match serde_json::from_str(input) {
+        Ok(js) => js,
+        Err(e) => {
+            eprintln!("error: unable to decode dive input: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let ascent_rate = js
+        .asc
+        .or(defaults.ascent_rate)
+        .unwrap_or(DEFAULT_ASCENT_RATE);
+    let descent_rate = js
+        .desc
+        .or(defaults.descent_rate)
+        .unwrap_or(DEFAULT_DESCENT_RATE);
+    let sac_bottom = js
+        .bottom_sac
+        .or(defaults.bottom_sac)
+        .unwrap_or(DEFAULT_BOTTOM_SAC);
+    let sac_deco = js
+        .deco_sac
+        .or(defaults.deco_sac)
+        .unwrap_or(DEFAULT_DECO_SAC);
+
+    let gf = match (js.gfl.or(defaults.gfl), js.gfh.or(defaults.gfh)) {
+        (Some(gfl), Some(gfh)) => GradientFactor::new(gfl, gfh),
+        _ => GradientFactor::default(),
     };
 
     let deco_gases = js
         .deco_gases
         .into_iter()
-        .map(|gas| {
-            (
-                Gas::new(gas.o2, gas.he, 100 - gas.he - gas.o2)
-                    .expect("unable to decode decompression gas"),
-                gas.max_operating_depth,
-            )
+        .map(|entry| {
+            let gas = decode_gas(entry.o2, entry.he, "decompression gas");
+            (gas, entry.max_operating_depth)
         })
         .collect::<Vec<(_, _)>>();
 
@@ -98,38 +306,114 @@ fn main() {
         .segments
         .into_iter()
         .map(|segment| {
-            (
-                Segment::new(
-                    SegmentType::Bottom,
-                    segment.depth,
-                    segment.depth,
-                    Duration::minutes(segment.time as i64),
-                    ascent_rate,
-                    descent_rate,
-                )
-                .expect("unable to decode segment"),
-                Gas::new(segment.o2, segment.he, 100 - segment.he - segment.o2)
-                    .expect("unable to decode bottom gas"),
+            let parsed_segment = Segment::new(
+                SegmentType::Bottom,
+                segment.depth,
+                segment.depth,
+                Duration::minutes(segment.time as i64),
+                ascent_rate,
+                descent_rate,
             )
+            .unwrap_or_else(|e| {
+                eprintln!("error: unable to decode segment: {:?}", e);
+                process::exit(1);
+            });
+            let gas = decode_gas(segment.o2, segment.he, "bottom gas");
+            (parsed_segment, gas)
         })
         .collect::<Vec<(_, _)>>();
 
+    let is_ccr = js.mode == JSONDiveMode::Ccr;
+    if is_ccr && js.setpoint.is_none() {
+        eprintln!("error: ccr mode requires a `setpoint`");
+        process::exit(1);
+    }
+
     let zhl16 = ZHL16Builder::new().gradient_factor(gf).finish();
 
+    let altitude = defaults.altitude.unwrap_or_default();
+    let environment = Environment::new(defaults.water_density(), altitude);
+
     let parameters = Parameters::new(
         ascent_rate,
         descent_rate,
-        Environment::new(SALTWATER, Altitude::default()),
+        Environment::new(defaults.water_density(), altitude),
         sac_bottom,
         sac_deco,
     );
 
-    let dive = OpenCircuit::new(zhl16, &bottom_segments, &deco_gases, parameters);
+    // For CCR, ZHL16 loading on the bottom segments is driven by the
+    // open-circuit-equivalent gas that reproduces the setpoint-derived PPO2
+    // at each depth; CCR gas usage is estimated separately by `ccr::gas_used`
+    // from the planned segments. `deco_gases` below is passed through unchanged, so
+    // the engine-generated ascent/deco-stop segments are NOT put through
+    // this substitution and are planned as ordinary open-circuit — see the
+    // "Known limitation" note on `ccr` for why.
+    let oc_segments: Vec<(Segment, Gas)> = if is_ccr {
+        let setpoint = js.setpoint.unwrap();
+        bottom_segments
+            .iter()
+            .map(|(segment, diluent)| {
+                let equivalent =
+                    ccr::equivalent_gas(*diluent, *segment.end_depth(), &environment, setpoint);
+                (segment.clone(), equivalent)
+            })
+            .collect()
+    } else {
+        bottom_segments.clone()
+    };
+
+    let dive = OpenCircuit::new(zhl16, &oc_segments, &deco_gases, parameters);
 
     let plan = dive.plan(); // Use this to include all AscDesc segments
 
-    let mut gas_plan = Vec::from_iter(plan.gas_used());
-    gas_plan.sort_by(|&(_, a), &(_, b)| b.cmp(&a));
+    if let Some(path) = plot_path {
+        plot::write_profile(&plan, path);
+    }
+
+    let ccr_usage = is_ccr.then(|| ccr::gas_used(&plan.segments(), &environment));
+
+    let o2_summary = oxygen_toxicity::summarize(&plan, &environment);
+    let mod_violations = oxygen_toxicity::mod_violations(&plan, &deco_gases);
+
+    let mut warned = false;
+    if o2_summary.cns_percent > 100.0 {
+        eprintln!(
+            "warning: CNS exposure is {:.0}%, exceeding the 100% single-exposure limit",
+            o2_summary.cns_percent
+        );
+        warned = true;
+    }
+    for (ppo2_depth, ppo2) in &o2_summary.cns_warnings {
+        eprintln!(
+            "warning: PPO2 of {:.2} ata at {}m exceeds 1.6 ata",
+            ppo2, ppo2_depth.0
+        );
+        warned = true;
+    }
+    for (depth, mod_depth) in &mod_violations {
+        eprintln!(
+            "warning: gas breathed at {}m, beyond its MOD of {}m",
+            depth.0, mod_depth.0
+        );
+        warned = true;
+    }
+
+    if json_output {
+        let json_plan = output::build_json_plan(
+            &plan,
+            ascent_rate.0,
+            descent_rate.0,
+            gf.low(),
+            gf.high(),
+            &o2_summary,
+            ccr_usage.as_ref(),
+        );
+        return RunOutcome {
+            warned,
+            json: Some(json_plan),
+        };
+    }
 
     println!("Ascent rate: {}m/min", ascent_rate.0);
     println!("Descent rate: {}m/min", descent_rate.0);
@@ -167,17 +451,36 @@ fn main() {
     }
     println!("{}", dive_plan_table);
 
-    let mut gas_plan_table = Table::new("{:>}  {:>}");
-    gas_plan_table.add_row(row!("Gas", "Amount"));
-    let mut total_gas = 0;
+    if let Some(ccr_usage) = &ccr_usage {
+        let mut ccr_table = Table::new("{:>}  {:>}");
+        ccr_table.add_row(row!("O2", format!("{} litres", ccr_usage.o2_litres)));
+        ccr_table.add_row(row!("Diluent", format!("{} litres", ccr_usage.diluent_litres)));
+        println!("{}", ccr_table);
+    } else {
+        let mut gas_plan = Vec::from_iter(plan.gas_used());
+        gas_plan.sort_by(|&(_, a), &(_, b)| b.cmp(&a));
+
+        let mut gas_plan_table = Table::new("{:>}  {:>}");
+        gas_plan_table.add_row(row!("Gas", "Amount"));
+        let mut total_gas = 0;
+
+        for (gas, qty) in gas_plan {
+            total_gas += qty;
+            let gas_str = format!("{}/{}", gas.o2(), gas.he());
+            let qty_str = format!("{} litres", qty);
+            gas_plan_table.add_row(row!(gas_str, qty_str));
+        }
+        gas_plan_table.add_row(row!("Total", format!("{} litres", total_gas)));
+        println!("{}", gas_plan_table);
+    }
+
+    let mut o2_table = Table::new("{:>}  {:>}");
+    o2_table.add_row(row!("CNS", format!("{:.0}%", o2_summary.cns_percent)));
+    o2_table.add_row(row!("OTU", format!("{:.0}", o2_summary.otu)));
+    println!("{}", o2_table);
 
-    for (gas, qty) in gas_plan {
-        total_gas += qty;
-        let gas_str = format!("{}/{}", gas.o2(), gas.he());
-        let qty_str = format!("{} litres", qty);
-        gas_plan_table.add_row(row!(gas_str, qty_str));
+    RunOutcome {
+        warned,
+        json: None,
     }
-    gas_plan_table.add_row(row!("Total", format!("{} litres", total_gas)));
-    println!("{}", gas_plan_table);
-    // println!("Total gas: {} litres", total_gas);
 }
@@ -0,0 +1,159 @@
+//! Machine-readable JSON representation of a computed dive plan.
+//!
+//! These types intentionally mirror `plan.segments()` / `plan.gas_used()`
+//! rather than reusing the `JSONDive*` input structs, since the input and
+//! output schemas are independent and should be free to evolve separately.
+
+use capra::plan::DivePlan;
+use capra::segment::SegmentType;
+use serde::Serialize;
+use time::Duration;
+
+use crate::ccr::CcrGasUsage;
+use crate::oxygen_toxicity::OxygenToxicitySummary;
+
+/// Bumped whenever a field is added, removed, or changes meaning, so
+/// downstream consumers can detect incompatible output.
+const SCHEMA_VERSION: u32 = 3;
+
+#[derive(Serialize, Debug)]
+pub struct JSONOutputGas {
+    pub o2: u8,
+    pub he: u8,
+}
+
+#[derive(Serialize, Debug)]
+pub struct JSONOutputSegment {
+    pub segment_type: String,
+    pub start_depth: i32,
+    pub end_depth: i32,
+    pub time_seconds: i64,
+    pub runtime_seconds: i64,
+    pub gas: JSONOutputGas,
+}
+
+#[derive(Serialize, Debug)]
+pub struct JSONOutputGasUsage {
+    pub gas: JSONOutputGas,
+    pub litres: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct JSONOutputCnsWarning {
+    pub depth: i32,
+    pub ppo2: f64,
+}
+
+/// Real O2/diluent consumption for a CCR dive, mirroring [`CcrGasUsage`].
+/// Present instead of `gas_used`/`total_gas_litres` being meaningful, since
+/// those are derived from the synthetic open-circuit-equivalent gas used
+/// only to drive ZHL16 loading, not what a rebreather diver actually burns.
+#[derive(Serialize, Debug)]
+pub struct JSONOutputCcrGasUsage {
+    pub o2_litres: i64,
+    pub diluent_litres: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct JSONOutputPlan {
+    pub schema_version: u32,
+    pub ascent_rate: i32,
+    pub descent_rate: i32,
+    pub gfl: u8,
+    pub gfh: u8,
+    pub segments: Vec<JSONOutputSegment>,
+    pub gas_used: Vec<JSONOutputGasUsage>,
+    pub total_gas_litres: i64,
+    pub ccr_gas_used: Option<JSONOutputCcrGasUsage>,
+    pub cns_percent: f64,
+    pub otu: f64,
+    pub cns_warnings: Vec<JSONOutputCnsWarning>,
+}
+
+/// Builds the JSON output document from a computed plan, re-deriving
+/// cumulative runtime the same way the table output does. For a CCR dive,
+/// pass the real `ccr_usage` so `ccr_gas_used` carries actual O2/diluent
+/// litres instead of `gas_used` reporting the synthetic equivalent gas.
+pub fn build_json_plan<T: DivePlan>(
+    plan: &T,
+    ascent_rate: i32,
+    descent_rate: i32,
+    gfl: u8,
+    gfh: u8,
+    o2_summary: &OxygenToxicitySummary,
+    ccr_usage: Option<&CcrGasUsage>,
+) -> JSONOutputPlan {
+    let mut runtime = Duration::zero();
+    let segments = plan
+        .segments()
+        .iter()
+        .map(|x| {
+            runtime += *x.0.time();
+            JSONOutputSegment {
+                segment_type: format!("{:?}", x.0.segment_type()),
+                start_depth: match x.0.segment_type() {
+                    SegmentType::AscDesc => x.0.start_depth().0,
+                    _ => x.0.end_depth().0,
+                },
+                end_depth: x.0.end_depth().0,
+                time_seconds: x.0.time().whole_seconds(),
+                runtime_seconds: runtime.whole_seconds(),
+                gas: JSONOutputGas {
+                    o2: x.1.o2(),
+                    he: x.1.he(),
+                },
+            }
+        })
+        .collect();
+
+    // For CCR, `plan.gas_used()` only totals the synthetic open-circuit
+    // equivalent gas substituted in to drive ZHL16 loading, not real
+    // consumption, so it's left empty in favour of `ccr_gas_used`.
+    let (gas_used, total_gas_litres) = if ccr_usage.is_some() {
+        (Vec::new(), 0)
+    } else {
+        let mut gas_used: Vec<(_, _)> = plan.gas_used().into_iter().collect();
+        gas_used.sort_by(|&(_, a), &(_, b)| b.cmp(&a));
+        let total_gas_litres = gas_used.iter().map(|&(_, qty)| qty).sum();
+        let gas_used = gas_used
+            .into_iter()
+            .map(|(gas, qty)| JSONOutputGasUsage {
+                gas: JSONOutputGas {
+                    o2: gas.o2(),
+                    he: gas.he(),
+                },
+                litres: qty,
+            })
+            .collect();
+        (gas_used, total_gas_litres)
+    };
+
+    let ccr_gas_used = ccr_usage.map(|usage| JSONOutputCcrGasUsage {
+        o2_litres: usage.o2_litres,
+        diluent_litres: usage.diluent_litres,
+    });
+
+    let cns_warnings = o2_summary
+        .cns_warnings
+        .iter()
+        .map(|(depth, ppo2)| JSONOutputCnsWarning {
+            depth: depth.0,
+            ppo2: *ppo2,
+        })
+        .collect();
+
+    JSONOutputPlan {
+        schema_version: SCHEMA_VERSION,
+        ascent_rate,
+        descent_rate,
+        gfl,
+        gfh,
+        segments,
+        gas_used,
+        total_gas_litres,
+        ccr_gas_used,
+        cns_percent: o2_summary.cns_percent,
+        otu: o2_summary.otu,
+        cns_warnings,
+    }
+}
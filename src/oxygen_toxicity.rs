@@ -0,0 +1,152 @@
+//! CNS% and OTU (pulmonary oxygen toxicity) tracking across a dive plan.
+//!
+//! Neither metric is modelled by the decompression engine itself, so this
+//! walks the already-computed plan and derives PPO2 per segment from the
+//! segment's `Gas` and ambient pressure.
+
+use capra::environment::Environment;
+use capra::plan::DivePlan;
+use capra::units::depth::Depth;
+
+/// NOAA single-exposure oxygen time limits, as (PPO2 in ata, limit in minutes).
+/// Must stay sorted ascending by PPO2; `cns_percent_for_segment` interpolates
+/// linearly between adjacent points.
+const NOAA_SINGLE_EXPOSURE_LIMITS: [(f64, f64); 7] = [
+    (1.0, 300.0),
+    (1.1, 240.0),
+    (1.2, 210.0),
+    (1.3, 180.0),
+    (1.4, 150.0),
+    (1.5, 120.0),
+    (1.6, 45.0),
+];
+
+/// Ambient pressure in ata at `depth` for the given environment: one
+/// atmosphere at the surface (reduced for altitude) plus one ata per 10m
+/// of saltwater.
+pub fn ata_at_depth(depth: Depth, environment: &Environment) -> f64 {
+    let surface_ata = 1.0 - environment.altitude().0 as f64 / 9000.0;
+    depth.0 as f64 / 10.0 + surface_ata
+}
+
+/// Partial pressure of oxygen in ata for `gas` at `depth`.
+pub fn ppo2_at_depth(gas: &capra::gas::Gas, depth: Depth, environment: &Environment) -> f64 {
+    ata_at_depth(depth, environment) * gas.o2() as f64 / 100.0
+}
+
+/// NOAA single-exposure limit in minutes for a given PPO2, linearly
+/// interpolated between table points. PPO2 below the table's lowest entry
+/// is treated as posing no CNS loading; above the highest entry the last
+/// (shortest) limit is used, since the caller separately flags >1.6 ata.
+fn noaa_limit_minutes(ppo2: f64) -> Option<f64> {
+    if ppo2 < NOAA_SINGLE_EXPOSURE_LIMITS[0].0 {
+        return None;
+    }
+    for window in NOAA_SINGLE_EXPOSURE_LIMITS.windows(2) {
+        let (lo_ppo2, lo_limit) = window[0];
+        let (hi_ppo2, hi_limit) = window[1];
+        if ppo2 <= hi_ppo2 {
+            let t = (ppo2 - lo_ppo2) / (hi_ppo2 - lo_ppo2);
+            return Some(lo_limit + t * (hi_limit - lo_limit));
+        }
+    }
+    Some(NOAA_SINGLE_EXPOSURE_LIMITS.last().unwrap().1)
+}
+
+/// OTU accrued for `minutes` at `ppo2`, per the Repex pulmonary toxicity formula.
+/// Zero below 0.5 ata, where pulmonary toxicity is not considered significant.
+fn otu_for_exposure(ppo2: f64, minutes: f64) -> f64 {
+    if ppo2 <= 0.5 {
+        return 0.0;
+    }
+    minutes * ((ppo2 - 0.5) / 0.5).powf(0.83)
+}
+
+#[derive(Debug, Default)]
+pub struct OxygenToxicitySummary {
+    pub cns_percent: f64,
+    pub otu: f64,
+    /// (end depth, PPO2) of segments whose PPO2 exceeded 1.6 ata.
+    pub cns_warnings: Vec<(Depth, f64)>,
+}
+
+/// Accumulates CNS% and OTU across every segment in the plan.
+pub fn summarize<T: DivePlan>(plan: &T, environment: &Environment) -> OxygenToxicitySummary {
+    let mut summary = OxygenToxicitySummary::default();
+
+    for (segment, gas) in plan.segments() {
+        let minutes = segment.time().whole_seconds() as f64 / 60.0;
+        let ppo2 = ppo2_at_depth(gas, *segment.end_depth(), environment);
+
+        if ppo2 > 1.6 {
+            summary.cns_warnings.push((*segment.end_depth(), ppo2));
+        }
+
+        if let Some(limit) = noaa_limit_minutes(ppo2) {
+            summary.cns_percent += minutes / limit * 100.0;
+        }
+
+        summary.otu += otu_for_exposure(ppo2, minutes);
+    }
+
+    summary
+}
+
+/// Segments that were breathed deeper than the gas's declared maximum
+/// operating depth, paired with that depth.
+pub fn mod_violations<T: DivePlan>(
+    plan: &T,
+    deco_gases: &[(capra::gas::Gas, Option<Depth>)],
+) -> Vec<(Depth, Depth)> {
+    let mut violations = Vec::new();
+    for (segment, gas) in plan.segments() {
+        let mod_depth = deco_gases
+            .iter()
+            .find(|(deco_gas, _)| deco_gas.o2() == gas.o2() && deco_gas.he() == gas.he())
+            .and_then(|(_, mod_depth)| *mod_depth);
+
+        if let Some(mod_depth) = mod_depth {
+            if segment.end_depth().0 > mod_depth.0 {
+                violations.push((*segment.end_depth(), mod_depth));
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noaa_limit_minutes_exact_table_point() {
+        assert_eq!(noaa_limit_minutes(1.4), Some(150.0));
+    }
+
+    #[test]
+    fn noaa_limit_minutes_interpolates_between_points() {
+        // Halfway between (1.0, 300.0) and (1.1, 240.0).
+        assert_eq!(noaa_limit_minutes(1.05), Some(270.0));
+    }
+
+    #[test]
+    fn noaa_limit_minutes_below_table_is_unlimited() {
+        assert_eq!(noaa_limit_minutes(0.5), None);
+    }
+
+    #[test]
+    fn noaa_limit_minutes_above_table_uses_shortest_limit() {
+        assert_eq!(noaa_limit_minutes(2.0), Some(45.0));
+    }
+
+    #[test]
+    fn otu_for_exposure_below_threshold_is_zero() {
+        assert_eq!(otu_for_exposure(0.5, 30.0), 0.0);
+    }
+
+    #[test]
+    fn otu_for_exposure_accrues_above_threshold() {
+        // PPO2 of 1.0 for 10 minutes: 10 * ((1.0-0.5)/0.5)^0.83 = 10.
+        assert!((otu_for_exposure(1.0, 10.0) - 10.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,88 @@
+//! Depth-versus-runtime chart of a computed dive plan.
+//!
+//! Writes an interactive HTML chart or a static image depending on the
+//! output path's extension, so `--plot profile.html` and `--plot
+//! profile.png` both do the obvious thing.
+
+use capra::plan::DivePlan;
+use capra::segment::SegmentType;
+use plotly::common::{Marker, Mode};
+use plotly::layout::Axis;
+use plotly::{ImageFormat, Layout, Plot, Scatter};
+use std::path::Path;
+use time::Duration;
+
+fn marker_color(segment_type: SegmentType) -> &'static str {
+    match segment_type {
+        SegmentType::AscDesc => "orange",
+        SegmentType::DecoStop => "red",
+        _ => "blue",
+    }
+}
+
+/// Renders `plan` as a depth-vs-runtime profile and writes it to `path`.
+/// The y-axis is inverted (deeper is lower), matching how divers read a
+/// profile. The whole dive is drawn as a single continuous line, in
+/// segment order, so the profile's shape matches the actual dive;
+/// `AscDesc` and `DecoStop` points are distinguished by marker color
+/// rather than by splitting them into their own traces. A gas switch
+/// between consecutive segments is annotated with the new mix.
+pub fn write_profile<T: DivePlan>(plan: &T, path: &Path) {
+    let mut runtime = Duration::zero();
+    let mut xs: Vec<f64> = Vec::new();
+    let mut ys: Vec<f64> = Vec::new();
+    let mut colors: Vec<&'static str> = Vec::new();
+    let mut gas_switch_annotations: Vec<(f64, f64, String)> = Vec::new();
+    let mut last_gas: Option<(u8, u8)> = None;
+
+    for (segment, gas) in plan.segments() {
+        runtime += *segment.time();
+        let x = runtime.whole_seconds() as f64;
+        let y = segment.end_depth().0 as f64;
+
+        xs.push(x);
+        ys.push(y);
+        colors.push(marker_color(segment.segment_type()));
+
+        let current_gas = (gas.o2(), gas.he());
+        if last_gas.is_some() && last_gas != Some(current_gas) {
+            gas_switch_annotations.push((x, y, format!("{}/{}", gas.o2(), gas.he())));
+        }
+        last_gas = Some(current_gas);
+    }
+
+    let mut plot = Plot::new();
+
+    let profile_trace = Scatter::new(xs.clone(), ys.clone())
+        .name("Dive Profile")
+        .mode(Mode::LinesMarkers)
+        .marker(Marker::new().color_array(colors.iter().map(|c| c.to_string()).collect()));
+    plot.add_trace(profile_trace);
+
+    for (x, y, label) in &gas_switch_annotations {
+        let switch_trace = Scatter::new(vec![*x], vec![*y])
+            .name(format!("Switch to {}", label))
+            .mode(Mode::Markers)
+            .marker(Marker::new().color("green").size(10));
+        plot.add_trace(switch_trace);
+    }
+
+    let layout = Layout::new()
+        .title("Dive Profile".into())
+        .x_axis(Axis::new().title("Runtime (s)".into()))
+        .y_axis(
+            Axis::new()
+                .title("Depth (m)".into())
+                .auto_range(false)
+                .range(vec![ys.iter().cloned().fold(0.0, f64::max), 0.0]),
+        );
+    plot.set_layout(layout);
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => plot.write_html(path),
+        Some("png") => plot.write_image(path, ImageFormat::PNG, 1280, 720, 1.0),
+        Some("svg") => plot.write_image(path, ImageFormat::SVG, 1280, 720, 1.0),
+        Some("jpg") | Some("jpeg") => plot.write_image(path, ImageFormat::JPEG, 1280, 720, 1.0),
+        _ => plot.write_html(path),
+    }
+}